@@ -0,0 +1,176 @@
+//! Networking: inbound packet dispatch (`PacketQueue`) and a
+//! priority-aware outbound send queue (`NetworkComponent`) so that
+//! large, low-priority sends (chunk data) can't head-of-line-block
+//! small, latency-sensitive ones (keep-alives, movement broadcasts).
+
+use feather_core::network::packet::{Packet, PacketType};
+use hashbrown::HashMap;
+use specs::storage::BTreeStorage;
+use specs::{Component, Entity};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Global resource buffering inbound packets by type until the system
+/// responsible for that packet type drains them via `for_packet`. The
+/// connection IO threads push packets in as they're received; nothing
+/// here assumes they arrive on the main tick thread.
+#[derive(Default)]
+pub struct PacketQueue {
+    packets: Mutex<HashMap<PacketType, Vec<(Entity, Box<Packet>)>>>,
+}
+
+impl PacketQueue {
+    /// Queues an inbound `packet` as having come from `player`.
+    pub fn push(&self, player: Entity, packet: Box<Packet>) {
+        self.packets
+            .lock()
+            .unwrap()
+            .entry(packet.ty())
+            .or_insert_with(Vec::new)
+            .push((player, packet));
+    }
+
+    /// Drains and returns every packet of type `ty` queued since the
+    /// last time it was drained.
+    pub fn for_packet(&self, ty: PacketType) -> Vec<(Entity, Box<Packet>)> {
+        self.packets
+            .lock()
+            .unwrap()
+            .remove(&ty)
+            .unwrap_or_default()
+    }
+}
+
+/// Priority class for an outbound packet. The flush step services all
+/// packets of the highest non-empty class before moving to the next.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum PacketPriority {
+    High,
+    Normal,
+    Background,
+}
+
+/// All priority classes, highest first — the order the flush step
+/// considers them in.
+const PRIORITIES: [PacketPriority; 3] = [
+    PacketPriority::High,
+    PacketPriority::Normal,
+    PacketPriority::Background,
+];
+
+/// Default priority for a packet type. Keep-alives and entity movement
+/// broadcasts default to high priority so chunk streaming never starves
+/// them; bulk world data defaults to background.
+fn default_priority(ty: PacketType) -> PacketPriority {
+    match ty {
+        PacketType::KeepAliveClientbound
+        | PacketType::PlayerPositionAndLookClientbound
+        | PacketType::EntityTeleport
+        | PacketType::EntityRelativeMove
+        | PacketType::EntityLookAndRelativeMove
+        | PacketType::EntityHeadLook => PacketPriority::High,
+        PacketType::ChunkData | PacketType::ChunkBatchStart | PacketType::ChunkBatchFinished => {
+            PacketPriority::Background
+        }
+        _ => PacketPriority::Normal,
+    }
+}
+
+/// Size, in bytes, of the frames oversized packets are split into.
+/// Splitting keeps one huge same-priority send (e.g. a chunk column)
+/// from monopolizing a flush round at the expense of another queued
+/// packet of the same priority — each gets a turn every round instead
+/// of being sent whole, back to back.
+const FRAME_SIZE: usize = 8192;
+
+/// A single queued outbound packet, encoded once up front and handed
+/// out one frame at a time.
+struct Queued {
+    bytes: Vec<u8>,
+    offset: usize,
+}
+
+impl Queued {
+    fn new(packet: &Packet) -> Self {
+        let mut bytes = Vec::with_capacity(packet.encoded_len());
+        packet
+            .encode(&mut bytes)
+            .expect("failed to encode outbound packet");
+        Self { bytes, offset: 0 }
+    }
+
+    /// Returns the next frame, advancing past it. Must not be called
+    /// again once `is_done` returns `true`.
+    fn next_frame(&mut self) -> &[u8] {
+        let end = (self.offset + FRAME_SIZE).min(self.bytes.len());
+        let frame = &self.bytes[self.offset..end];
+        self.offset = end;
+        frame
+    }
+
+    fn is_done(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+}
+
+/// Component holding a player's outbound packets, bucketed by
+/// priority. `send_packet_to_player` tags each packet with its default
+/// priority and pushes it into the matching bucket; `flush_player_queue`
+/// is called once per tick to drain them.
+#[derive(Default)]
+pub struct NetworkComponent {
+    queues: Mutex<[VecDeque<Queued>; 3]>,
+}
+
+impl Component for NetworkComponent {
+    type Storage = BTreeStorage<Self>;
+}
+
+fn bucket(priority: PacketPriority) -> usize {
+    match priority {
+        PacketPriority::High => 0,
+        PacketPriority::Normal => 1,
+        PacketPriority::Background => 2,
+    }
+}
+
+/// Queues `packet` for sending to the player owning `net`, tagging it
+/// with its default priority.
+pub fn send_packet_to_player<P>(net: &NetworkComponent, packet: P)
+where
+    P: Packet + 'static,
+{
+    let priority = default_priority(packet.ty());
+    let queued = Queued::new(&packet);
+    let mut queues = net.queues.lock().unwrap();
+    queues[bucket(priority)].push_back(queued);
+}
+
+/// Drains every currently-queued packet in `net` into `send_frame`, one
+/// priority class at a time, highest first — this is what keeps chunk
+/// streaming (background) from ever delaying a keep-alive or movement
+/// packet (high). Every priority class is serviced on every call (never
+/// just the first non-empty one). The queues stay locked for the whole
+/// flush, so a packet queued while a flush is running waits for the next
+/// call.
+///
+/// Within a class, queued packets are drained one frame at a time rather
+/// than whole: each packet hands over at most `FRAME_SIZE` bytes per round
+/// and, if it isn't finished, goes back to the end of the queue. This is
+/// what lets two large same-priority sends (e.g. two chunk columns) share
+/// a flush fairly instead of one completing before the other starts.
+/// `send_frame` is expected to forward each frame to the connection in the
+/// order it's handed over.
+pub fn flush_player_queue(net: &NetworkComponent, mut send_frame: impl FnMut(&[u8])) {
+    let mut queues = net.queues.lock().unwrap();
+
+    for priority in PRIORITIES {
+        let queue = &mut queues[bucket(priority)];
+        while let Some(mut queued) = queue.pop_front() {
+            send_frame(queued.next_frame());
+            if !queued.is_done() {
+                queue.push_back(queued);
+            }
+        }
+    }
+}
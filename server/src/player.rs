@@ -7,20 +7,74 @@ use crate::entity::{broadcast_entity_movement, EntityComponent, PlayerComponent}
 use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
 use feather_core::network::cast_packet;
 use feather_core::network::packet::implementation::{
-    ChunkData, PlayerLook, PlayerPosition, PlayerPositionAndLookServerbound,
+    ChunkBatchFinished, ChunkBatchReceived, ChunkBatchStart, ChunkData, PlayerLook,
+    PlayerPosition, PlayerPositionAndLookClientbound, PlayerPositionAndLookServerbound,
+    TeleportConfirm, UnloadChunk,
 };
 use feather_core::network::packet::{Packet, PacketType};
 use feather_core::world::chunk::Chunk;
 use feather_core::world::{ChunkMap, ChunkPosition, Position};
 use hashbrown::HashSet;
+use log::warn;
 use rayon::prelude::*;
 use shrev::EventChannel;
 use specs::storage::BTreeStorage;
 use specs::{
-    Component, Entities, Entity, LazyUpdate, ParJoin, Read, ReadStorage, ReaderId, System, World,
-    WorldExt, WriteStorage,
+    Component, Entities, Entity, Join, LazyUpdate, ParJoin, Read, ReadStorage, ReaderId, System,
+    World, WorldExt, WriteStorage,
 };
+use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
+use std::time::Instant;
+
+/// A new batch is never started for a player while this many
+/// previously-sent batches remain unacknowledged.
+const MAX_UNACKED_BATCHES: usize = 10;
+/// Floor on `chunks_per_tick` so a slow/unresponsive client still
+/// trickles chunks rather than stalling completely.
+const MIN_CHUNKS_PER_TICK: f32 = 1.0;
+/// Ceiling on `chunks_per_tick` so a single player can't monopolize
+/// a tick's worth of chunk sending.
+const MAX_CHUNKS_PER_TICK: f32 = 64.0;
+/// Starting rate used until the first batch has been acknowledged.
+const INITIAL_CHUNKS_PER_TICK: f32 = 10.0;
+/// Budget, in milliseconds, that sending a batch of chunks to one
+/// player is allowed to cost per tick before the rate is throttled
+/// down; this mirrors the server's target tick length.
+const TARGET_MILLIS_PER_TICK: f32 = 50.0;
+/// How many chunks out from a player, in any direction, chunks are
+/// loaded and sent.
+const VIEW_DISTANCE: i32 = 10;
+/// Generous per-tick sanity bound on horizontal movement, in blocks.
+/// This isn't meant to catch subtle speed hacks (tick timing jitter
+/// makes that unreliable) — it's meant to catch blatant teleport/fly
+/// hacks that a trust-the-client implementation has no defense
+/// against at all.
+const MAX_HORIZONTAL_DELTA: f64 = 100.0;
+/// Generous per-tick sanity bound on vertical movement, in blocks.
+const MAX_VERTICAL_DELTA: f64 = 100.0;
+
+/// Returns the chunk positions within `view_distance` chunks of
+/// `center`, ordered by increasing squared radius so the column the
+/// player stands in comes first, followed by each ring outward —
+/// the classic circular chunk load order.
+fn spiral_chunk_order(center: ChunkPosition, view_distance: i32) -> Vec<ChunkPosition> {
+    let mut offsets = Vec::with_capacity((2 * view_distance as usize + 1).pow(2));
+    for dx in -view_distance..=view_distance {
+        for dz in -view_distance..=view_distance {
+            let r_squared = dx * dx + dz * dz;
+            if r_squared <= view_distance * view_distance {
+                offsets.push((r_squared, dx, dz));
+            }
+        }
+    }
+    offsets.sort_unstable_by_key(|(r_squared, _, _)| *r_squared);
+
+    offsets
+        .into_iter()
+        .map(|(_, dx, dz)| ChunkPosition::new(center.x + dx, center.z + dz))
+        .collect()
+}
 
 /// System for handling player movement
 /// packets.
@@ -32,12 +86,52 @@ impl<'a> System<'a> for PlayerMovementSystem {
         ReadStorage<'a, PlayerComponent>,
         Read<'a, PacketQueue>,
         ReadStorage<'a, NetworkComponent>,
+        WriteStorage<'a, ChunkPendingComponent>,
+        WriteStorage<'a, ChunkLoadedComponent>,
+        WriteStorage<'a, TeleportComponent>,
+        Read<'a, ChunkMap>,
         Entities<'a>,
-        Read<'a, LazyUpdate>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut ecomps, pcomps, packet_queue, netcomps, entities, _) = data;
+        let (
+            mut ecomps,
+            pcomps,
+            packet_queue,
+            netcomps,
+            mut pendings,
+            mut loadeds,
+            mut teleports,
+            chunk_map,
+            entities,
+        ) = data;
+
+        // Nothing attaches `TeleportComponent` when a player entity is
+        // created; lazily attach a default to every player missing one,
+        // mirroring `ChunkSendSystem`'s lazy-attach of `ChunkBatchComponent`.
+        // Without this, the teleport-confirm handshake below has nowhere
+        // to store its state: the pending-teleport check always reads
+        // `None` and a sanity-check violation is never actually corrected
+        // client-side.
+        let to_init: Vec<Entity> = (&entities, &pcomps)
+            .join()
+            .filter(|(player, _)| !teleports.contains(*player))
+            .map(|(player, _)| player)
+            .collect();
+        for player in to_init {
+            teleports
+                .insert(player, TeleportComponent::default())
+                .unwrap();
+        }
+
+        // Teleport confirmations clear the pending handshake so the
+        // player's movement packets are trusted again.
+        for (player, packet) in packet_queue.for_packet(PacketType::TeleportConfirm) {
+            let packet = cast_packet::<TeleportConfirm>(&packet);
+            if let Some(teleport) = teleports.get_mut(player) {
+                teleport.confirm(packet.teleport_id);
+            }
+        }
 
         // Take movement packets
         let mut packets = vec![];
@@ -47,15 +141,38 @@ impl<'a> System<'a> for PlayerMovementSystem {
 
         // Handle movement packets
         for (player, packet) in packets {
+            // While a teleport this player was sent is unconfirmed,
+            // ignore their movement packets entirely: they're either
+            // stale (sent before the teleport arrived client-side) or
+            // from a client that hasn't caught up yet, and trusting
+            // them would fight the server-authoritative position.
+            if teleports.get(player).map_or(false, TeleportComponent::is_pending) {
+                continue;
+            }
+
             let ecomp = ecomps.get(player).unwrap();
+            let old_pos = ecomp.position;
 
             // Get position using packet and old position
-            let (new_pos, has_moved, has_looked) = new_pos_from_packet(ecomp.position, packet);
+            let (reported_pos, has_moved, has_looked) = new_pos_from_packet(old_pos, packet);
+            let (new_pos, suspected_violation) = validate_movement(old_pos, reported_pos);
+
+            if suspected_violation {
+                warn!(
+                    "rejecting movement from player {:?}: {:?} -> {:?} exceeds sanity bounds",
+                    player, old_pos, reported_pos
+                );
+                if let (Some(net), Some(teleport)) =
+                    (netcomps.get(player), teleports.get_mut(player))
+                {
+                    teleport_player(net, teleport, new_pos);
+                }
+            }
 
             // Broadcast position update
             broadcast_entity_movement(
                 player,
-                ecomp.position,
+                old_pos,
                 new_pos,
                 has_moved,
                 has_looked,
@@ -66,10 +183,109 @@ impl<'a> System<'a> for PlayerMovementSystem {
 
             // Set new position
             ecomps.get_mut(player).unwrap().position = new_pos;
+
+            if has_moved
+                && !suspected_violation
+                && ChunkPosition::from(old_pos) != ChunkPosition::from(new_pos)
+            {
+                if let (Some(net), Some(pending), Some(loaded)) = (
+                    netcomps.get(player),
+                    pendings.get_mut(player),
+                    loadeds.get_mut(player),
+                ) {
+                    update_loaded_chunks(
+                        ChunkPosition::from(new_pos),
+                        net,
+                        pending,
+                        loaded,
+                        &chunk_map,
+                    );
+                }
+            }
         }
     }
 }
 
+/// Validates a client-reported position against the player's last
+/// accepted position. NaN/infinite coordinates are rejected outright;
+/// a delta beyond the per-tick sanity bounds is treated as a
+/// suspected speed/fly violation. In either case, the old position is
+/// returned and the caller is expected to re-sync the client via a
+/// teleport.
+fn validate_movement(old_pos: Position, reported_pos: Position) -> (Position, bool) {
+    if !reported_pos.x.is_finite() || !reported_pos.y.is_finite() || !reported_pos.z.is_finite() {
+        return (old_pos, true);
+    }
+
+    let dx = reported_pos.x - old_pos.x;
+    let dy = reported_pos.y - old_pos.y;
+    let dz = reported_pos.z - old_pos.z;
+    let horizontal = dx.hypot(dz);
+
+    if horizontal > MAX_HORIZONTAL_DELTA || dy.abs() > MAX_VERTICAL_DELTA {
+        return (old_pos, true);
+    }
+
+    (reported_pos, false)
+}
+
+/// Relocates a player and begins a new teleport-confirm handshake:
+/// incoming movement packets are ignored until the client echoes the
+/// assigned teleport id back via a teleport-confirm packet.
+fn teleport_player(net: &NetworkComponent, teleport: &mut TeleportComponent, pos: Position) {
+    let id = teleport.begin();
+    send_packet_to_player(
+        net,
+        PlayerPositionAndLookClientbound::new(pos.x, pos.y, pos.z, pos.yaw, pos.pitch, 0, id),
+    );
+}
+
+/// Diffs the chunks within view distance (a disc, as given by
+/// `spiral_chunk_order`) around `new_chunk` against the
+/// chunks a player was previously tracking: newly in-range chunks are
+/// marked pending so `ChunkSendSystem` picks them up under its normal
+/// per-tick rate limit (whether already loaded or not), and chunks
+/// that fell out of range are unloaded client-side and released.
+fn update_loaded_chunks(
+    new_chunk: ChunkPosition,
+    net: &NetworkComponent,
+    pending: &mut ChunkPendingComponent,
+    loaded: &mut ChunkLoadedComponent,
+    chunk_map: &ChunkMap,
+) {
+    let in_range: HashSet<ChunkPosition> =
+        spiral_chunk_order(new_chunk, VIEW_DISTANCE).into_iter().collect();
+
+    let to_load: Vec<ChunkPosition> = in_range
+        .iter()
+        .copied()
+        .filter(|pos| !loaded.contains(pos))
+        .collect();
+    let to_unload: Vec<ChunkPosition> = loaded
+        .iter()
+        .copied()
+        .filter(|pos| !in_range.contains(pos))
+        .collect();
+
+    for pos in to_load {
+        // Don't send immediately even if the chunk is already loaded:
+        // that would bypass `ChunkSendSystem`'s batching/rate limit for
+        // exactly the case (chunks already resident, e.g. spawn area
+        // or overlap with another player) that's most likely to flood
+        // a boundary-crossing player's connection.
+        pending.insert(pos);
+        chunk_map.add_ref(pos);
+        loaded.insert(pos);
+    }
+
+    for pos in to_unload {
+        send_packet_to_player(net, UnloadChunk::new(pos.x, pos.z));
+        chunk_map.remove_ref(pos);
+        pending.remove(&pos);
+        loaded.remove(&pos);
+    }
+}
+
 fn new_pos_from_packet(old_pos: Position, packet: Box<Packet>) -> (Position, bool, bool) {
     let mut has_looked = false;
     let mut has_moved = false;
@@ -131,9 +347,108 @@ impl Component for ChunkPendingComponent {
     type Storage = BTreeStorage<Self>;
 }
 
+/// Component storing the set of chunks a player currently has loaded
+/// (sent or pending), used to diff against their view distance as
+/// they move so newly in-range chunks can be queued and newly
+/// out-of-range ones unloaded.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkLoadedComponent {
+    pub loaded: HashSet<ChunkPosition>,
+}
+
+impl Deref for ChunkLoadedComponent {
+    type Target = HashSet<ChunkPosition>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.loaded
+    }
+}
+
+impl DerefMut for ChunkLoadedComponent {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.loaded
+    }
+}
+
+impl Component for ChunkLoadedComponent {
+    type Storage = BTreeStorage<Self>;
+}
+
+/// Component tracking the teleport-confirm handshake for a player.
+/// While a teleport is pending, incoming movement packets for that
+/// player are ignored until the client echoes the assigned id back.
+#[derive(Clone, Debug, Default)]
+pub struct TeleportComponent {
+    next_id: i32,
+    pending_id: Option<i32>,
+}
+
+impl TeleportComponent {
+    /// Assigns and stores a new teleport id, returning it.
+    fn begin(&mut self) -> i32 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending_id = Some(self.next_id);
+        self.next_id
+    }
+
+    /// Clears the pending handshake if `id` matches the outstanding one.
+    fn confirm(&mut self, id: i32) {
+        if self.pending_id == Some(id) {
+            self.pending_id = None;
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending_id.is_some()
+    }
+}
+
+impl Component for TeleportComponent {
+    type Storage = BTreeStorage<Self>;
+}
+
+/// Component tracking adaptive chunk-batch flow control state for a
+/// player, modeled on the vanilla chunk-batch protocol: chunks are sent
+/// in batches bounded by a "batch start"/"batch finished" pair, and the
+/// client acknowledges each batch once it's processed it. The observed
+/// ack latency is used to raise or lower how many chunks we attempt to
+/// push per tick, so a fast client gets its world streamed in quickly
+/// while a slow one doesn't get its connection saturated.
+#[derive(Clone, Debug)]
+pub struct ChunkBatchComponent {
+    /// Number of batches sent to this player that haven't been
+    /// acknowledged yet.
+    pub unacked_batches: usize,
+    /// Current number of chunks to send per tick.
+    pub chunks_per_tick: f32,
+    /// Size and start time of every batch that's been sent but not yet
+    /// acknowledged, oldest first. Acks arrive in the order batches were
+    /// sent, so popping the front always matches an ack to the batch it
+    /// actually belongs to, even with several in flight at once.
+    in_flight: VecDeque<(usize, Instant)>,
+}
+
+impl Default for ChunkBatchComponent {
+    fn default() -> Self {
+        Self {
+            unacked_batches: 0,
+            chunks_per_tick: INITIAL_CHUNKS_PER_TICK,
+            in_flight: VecDeque::new(),
+        }
+    }
+}
+
+impl Component for ChunkBatchComponent {
+    type Storage = BTreeStorage<Self>;
+}
+
 /// System for sending chunks to players once they're loaded.
 ///
-/// This system listens to `ChunkLoadEvent`s.
+/// On each tick this system drains each player's `ChunkPendingComponent`,
+/// sending the pending chunks that are present in `ChunkMap`, at a rate
+/// governed by their `ChunkBatchComponent`. `ChunkLoadEvent`s are read
+/// only to keep the reader current; which chunks are available is taken
+/// from `ChunkMap`, not from the events.
 pub struct ChunkSendSystem {
     load_event_reader: Option<ReaderId<ChunkLoadEvent>>,
 }
@@ -149,29 +464,99 @@ impl ChunkSendSystem {
 impl<'a> System<'a> for ChunkSendSystem {
     type SystemData = (
         WriteStorage<'a, ChunkPendingComponent>,
+        WriteStorage<'a, ChunkBatchComponent>,
+        ReadStorage<'a, EntityComponent>,
         ReadStorage<'a, NetworkComponent>,
         Read<'a, ChunkMap>,
         Read<'a, EventChannel<ChunkLoadEvent>>,
+        Read<'a, PacketQueue>,
+        Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut pendings, netcomps, chunk_map, load_events) = data;
-
-        for event in load_events.read(&mut self.load_event_reader.as_mut().unwrap()) {
-            // TODO perhaps this is slightly inefficient?
-            (&netcomps, &mut pendings)
-                .par_join()
-                .for_each(|(net, pending)| {
-                    if pending.contains(&event.pos) {
-                        // It's safe to unwrap the chunk value now,
-                        // because we know it's been loaded.
-                        let chunk = chunk_map.chunk_at(event.pos).unwrap();
-                        send_chunk_data(chunk, net);
-
-                        pending.remove(&event.pos);
-                    }
-                });
+        let (
+            mut pendings,
+            mut batches,
+            ecomps,
+            netcomps,
+            chunk_map,
+            load_events,
+            packet_queue,
+            entities,
+        ) = data;
+
+        // Drain the event channel so the reader doesn't fall behind; the
+        // loop below finds available chunks by looking them up in
+        // `ChunkMap`, and the actual send is rate-limited there.
+        for _event in load_events.read(&mut self.load_event_reader.as_mut().unwrap()) {}
+
+        // Apply acks before deciding how many chunks to send this tick,
+        // so a just-arrived ack can raise the rate in the same tick.
+        for (player, packet) in packet_queue.for_packet(PacketType::ChunkBatchReceived) {
+            let _packet = cast_packet::<ChunkBatchReceived>(&packet);
+            if let Some(batch) = batches.get_mut(player) {
+                apply_batch_ack(batch);
+            }
+        }
+
+        // Nothing currently attaches `ChunkBatchComponent` when a player
+        // entity is created, but the send loop below needs one on every
+        // player to track its rate; lazily attach a default rather than
+        // excluding every player with no batch state yet from the join.
+        let to_init: Vec<Entity> = (&entities, &ecomps, &netcomps, &pendings)
+            .join()
+            .filter(|(player, ..)| !batches.contains(*player))
+            .map(|(player, ..)| player)
+            .collect();
+        for player in to_init {
+            batches
+                .insert(player, ChunkBatchComponent::default())
+                .unwrap();
         }
+
+        (&ecomps, &netcomps, &mut pendings, &mut batches)
+            .par_join()
+            .for_each(|(ecomp, net, pending, batch)| {
+                if batch.unacked_batches >= MAX_UNACKED_BATCHES || pending.is_empty() {
+                    return;
+                }
+
+                let center = ChunkPosition::from(ecomp.position);
+                let limit = batch.chunks_per_tick.floor().max(1.0) as usize;
+                let mut sent: Vec<(ChunkPosition, Chunk)> =
+                    Vec::with_capacity(limit.min(pending.len()));
+                // Walk candidate positions nearest-first so chunks arrive
+                // in the classic circular load order rather than in
+                // whatever arbitrary order `HashSet` iteration gives us.
+                // Each candidate is looked up in `ChunkMap` exactly once,
+                // and the chunk that lookup returns is cloned into the
+                // batch, so the batch holds exactly what was found.
+                for pos in spiral_chunk_order(center, VIEW_DISTANCE) {
+                    if sent.len() >= limit {
+                        break;
+                    }
+                    if !pending.contains(&pos) {
+                        continue;
+                    }
+                    if let Some(chunk) = chunk_map.chunk_at(pos) {
+                        sent.push((pos, chunk.clone()));
+                    }
+                }
+
+                if sent.is_empty() {
+                    return;
+                }
+
+                send_packet_to_player(net, ChunkBatchStart::new());
+                for (pos, chunk) in &sent {
+                    send_chunk_data(chunk, net);
+                    pending.remove(pos);
+                }
+                send_packet_to_player(net, ChunkBatchFinished::new(sent.len() as i32));
+
+                batch.unacked_batches += 1;
+                batch.in_flight.push_back((sent.len(), Instant::now()));
+            });
     }
 
     fn setup(&mut self, world: &mut World) {
@@ -185,6 +570,27 @@ impl<'a> System<'a> for ChunkSendSystem {
     }
 }
 
+/// Updates a player's `ChunkBatchComponent` in response to a
+/// chunk-batch-received ack, deriving a per-chunk cost from the
+/// elapsed time since the batch was started and the number of chunks
+/// it contained, then adjusting `chunks_per_tick` so that sending a
+/// batch costs roughly `TARGET_MILLIS_PER_TICK`.
+fn apply_batch_ack(batch: &mut ChunkBatchComponent) {
+    batch.unacked_batches = batch.unacked_batches.saturating_sub(1);
+
+    if let Some((batch_size, started_at)) = batch.in_flight.pop_front() {
+        if batch_size == 0 {
+            return;
+        }
+
+        let elapsed_millis = started_at.elapsed().as_secs_f32() * 1000.0;
+        let per_chunk_cost = (elapsed_millis / batch_size as f32).max(0.001);
+
+        batch.chunks_per_tick = (TARGET_MILLIS_PER_TICK / per_chunk_cost)
+            .clamp(MIN_CHUNKS_PER_TICK, MAX_CHUNKS_PER_TICK);
+    }
+}
+
 fn send_chunk_data(chunk: &Chunk, net: &NetworkComponent) {
     let packet = ChunkData::new(chunk.clone());
     send_packet_to_player(net, packet);
@@ -216,3 +622,81 @@ pub fn send_chunk_to_player(
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: f64, y: f64, z: f64) -> Position {
+        Position::new(x, y, z, 0.0, 0.0)
+    }
+
+    #[test]
+    fn small_move_is_accepted() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(1.0, 64.0, 1.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(!violation);
+        assert_eq!(result.x, new.x);
+        assert_eq!(result.y, new.y);
+        assert_eq!(result.z, new.z);
+    }
+
+    #[test]
+    fn move_exactly_at_horizontal_threshold_is_accepted() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(MAX_HORIZONTAL_DELTA, 64.0, 0.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(!violation);
+        assert_eq!(result.x, new.x);
+    }
+
+    #[test]
+    fn move_just_over_horizontal_threshold_is_rejected() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(MAX_HORIZONTAL_DELTA + 1.0, 64.0, 0.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(violation);
+        assert_eq!(result.x, old.x);
+        assert_eq!(result.z, old.z);
+    }
+
+    #[test]
+    fn move_just_over_vertical_threshold_is_rejected() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(0.0, 64.0 + MAX_VERTICAL_DELTA + 1.0, 0.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(violation);
+        assert_eq!(result.y, old.y);
+    }
+
+    #[test]
+    fn nan_coordinate_is_rejected() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(f64::NAN, 64.0, 0.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(violation);
+        assert_eq!(result.x, old.x);
+    }
+
+    #[test]
+    fn infinite_coordinate_is_rejected() {
+        let old = pos(0.0, 64.0, 0.0);
+        let new = pos(0.0, f64::INFINITY, 0.0);
+
+        let (result, violation) = validate_movement(old, new);
+
+        assert!(violation);
+        assert_eq!(result.y, old.y);
+    }
+}